@@ -0,0 +1,194 @@
+//! Structured diagnostics parsed out of a TeX engine's `.log` output.
+//!
+//! Mirrors how LaTeX-aware tooling (texlab) turns the raw, line-oriented
+//! engine log into file/line diagnostics instead of making callers grep
+//! through it themselves.
+
+use std::fmt;
+
+/// How severe a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A single finding extracted from the engine log, tied back to the
+/// source file and line it originated from where the log makes that
+/// recoverable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+/// Parse a full engine log into a list of diagnostics.
+///
+/// Tracks the `(filename ...)` nesting the engine prints to attribute
+/// each finding to the file being processed at that point, and picks up:
+/// - `! <message>` followed by `l.<n> <context>` (errors)
+/// - `LaTeX Warning: <message>` (warnings)
+/// - `Overfull \hbox`/`Underfull \hbox` box warnings
+pub(crate) fn parse(log: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut file_stack: Vec<String> = Vec::new();
+    let lines: Vec<&str> = log.lines().collect();
+
+    for (i, line) in lines.iter().enumerate() {
+        track_file_stack(line, &mut file_stack);
+
+        if let Some(message) = line.strip_prefix("! ") {
+            let following_line = lines[i + 1..].iter().take(3).find_map(|l| parse_line_marker(l));
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                file: file_stack.last().cloned(),
+                line: following_line,
+                message: message.trim().to_string(),
+            });
+        } else if let Some(message) = line.strip_prefix("LaTeX Warning: ") {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                file: file_stack.last().cloned(),
+                line: parse_input_line(message),
+                message: strip_input_line(message),
+            });
+        } else if line.starts_with("Overfull") || line.starts_with("Underfull") {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                file: file_stack.last().cloned(),
+                line: parse_input_line(line),
+                message: line.trim().to_string(),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Whether the log requests another compile pass.
+pub(crate) fn needs_rerun(log: &str) -> bool {
+    log.contains("Rerun to get cross-references right") || log.contains("Label(s) may have changed")
+}
+
+/// Parse a `l.<n> <context>` marker that follows a `! <message>` error.
+fn parse_line_marker(line: &str) -> Option<u64> {
+    let rest = line.trim_start().strip_prefix("l.")?;
+    take_digits(rest)
+}
+
+/// Parse the `... on input line 42.` suffix LaTeX appends to warnings.
+fn parse_input_line(message: &str) -> Option<u64> {
+    let idx = message.find("on input line ")?;
+    take_digits(&message[idx + "on input line ".len()..])
+}
+
+fn strip_input_line(message: &str) -> String {
+    match message.find(" on input line ") {
+        Some(idx) => message[..idx].trim().to_string(),
+        None => message.trim().to_string(),
+    }
+}
+
+fn take_digits(s: &str) -> Option<u64> {
+    let digits: String = s.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Update `stack` based on the `(filename ...)`/`)` nesting a log line
+/// contributes. The engine opens a paren right before the name of every
+/// file it starts processing and closes it once that file is done.
+fn track_file_stack(line: &str, stack: &mut Vec<String>) {
+    for token in line.split_whitespace() {
+        if let Some(rest) = token.strip_prefix('(') {
+            let closes = rest.chars().rev().take_while(|&c| c == ')').count();
+            let name = &rest[..rest.len() - closes];
+            if looks_like_source_file(name) {
+                stack.push(name.to_string());
+                // The compact form `(./package.sty)` opens and closes the
+                // file on the same token; pop it right back off instead
+                // of leaving it dangling for an unrelated later `)` to pop.
+                for _ in 0..closes {
+                    stack.pop();
+                }
+            }
+        } else if !token.is_empty() && token.chars().all(|c| c == ')') {
+            for _ in token.chars() {
+                stack.pop();
+            }
+        }
+    }
+}
+
+fn looks_like_source_file(name: &str) -> bool {
+    name.ends_with(".tex") || name.ends_with(".sty") || name.ends_with(".cls")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_with_line_marker_and_file() {
+        let log = "(main.tex\n! Undefined control sequence.\nl.5 \\foo\n)\n";
+        let diagnostics = parse(log);
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                severity: Severity::Error,
+                file: Some("main.tex".to_string()),
+                line: Some(5),
+                message: "Undefined control sequence.".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_warning_strips_input_line_suffix() {
+        let log = "LaTeX Warning: Reference `fig1' on page 1 undefined on input line 42.\n";
+        let diagnostics = parse(log);
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                severity: Severity::Warning,
+                file: None,
+                line: Some(42),
+                message: "Reference `fig1' on page 1 undefined".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_needs_rerun_detects_sentinel_phrases() {
+        assert!(needs_rerun("LaTeX Warning: Label(s) may have changed. Rerun to get cross-references right.\n"));
+        assert!(!needs_rerun("Output written on main.pdf (1 page).\n"));
+    }
+
+    #[test]
+    fn test_parse_attributes_warning_after_compact_package_load() {
+        let log = "(main.tex (./package.sty)\nLaTeX Warning: Reference `fig1' on page 1 undefined\n)\n";
+        let diagnostics = parse(log);
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                severity: Severity::Warning,
+                file: Some("main.tex".to_string()),
+                line: None,
+                message: "Reference `fig1' on page 1 undefined".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! TeX engine detection.
+//!
+//! Instead of hard-coding `pdflatex`, probe `PATH` for the requested
+//! engine and fall back across the other known engines if it is absent.
+//! Modeled after texlab's `tex::Distribution` resolver: each engine knows
+//! its own invocation flags, so [`LatexCompiler`](crate::LatexCompiler)
+//! can build a working command line without the caller having to call
+//! `with_cmd`/`with_args` by hand.
+
+use std::process::{Command, Stdio};
+
+use crate::{LatexError, Result};
+
+/// A TeX engine known to this crate.
+///
+/// `Custom` allows pointing at an engine (or wrapper, e.g. `latexmk`) that
+/// is not one of the built-in variants.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TexEngine {
+    PdfLatex,
+    XeLatex,
+    LuaLatex,
+    Custom(String),
+}
+
+/// The order engines are tried in when no specific engine is requested.
+const KNOWN_ENGINES: &[TexEngine] = &[TexEngine::PdfLatex, TexEngine::XeLatex, TexEngine::LuaLatex];
+
+impl TexEngine {
+    /// The executable name as it is looked up on `PATH`.
+    pub fn binary(&self) -> &str {
+        match self {
+            TexEngine::PdfLatex => "pdflatex",
+            TexEngine::XeLatex => "xelatex",
+            TexEngine::LuaLatex => "lualatex",
+            TexEngine::Custom(bin) => bin,
+        }
+    }
+
+    /// The flags this engine should always be invoked with.
+    ///
+    /// `shell_escape` is opt-in since it allows the document to run
+    /// arbitrary shell commands during compilation.
+    pub fn default_args(&self, shell_escape: bool) -> Vec<String> {
+        let mut args = vec!["-interaction=nonstopmode".to_string()];
+        if shell_escape {
+            args.push("-shell-escape".to_string());
+        }
+        args
+    }
+
+    /// Probe `PATH` for this engine's binary.
+    fn is_available(&self) -> bool {
+        Command::new(self.binary())
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+
+    /// Resolve `preferred` to an available engine, falling back across
+    /// [`KNOWN_ENGINES`] if it cannot be found on `PATH`.
+    ///
+    /// A `Custom` engine is never substituted: if the user asked for a
+    /// specific binary, report it missing instead of silently picking a
+    /// different one.
+    pub fn resolve(preferred: Option<TexEngine>) -> Result<TexEngine> {
+        if let Some(engine) = preferred {
+            return match engine {
+                TexEngine::Custom(_) => {
+                    if engine.is_available() {
+                        Ok(engine)
+                    } else {
+                        Err(LatexError::EngineNotFound(engine.binary().to_string()))
+                    }
+                }
+                _ if engine.is_available() => Ok(engine),
+                _ => Self::resolve(None),
+            };
+        }
+
+        KNOWN_ENGINES
+            .iter()
+            .find(|engine| engine.is_available())
+            .cloned()
+            .ok_or_else(|| {
+                LatexError::EngineNotFound(
+                    KNOWN_ENGINES
+                        .iter()
+                        .map(TexEngine::binary)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+            })
+    }
+}
@@ -0,0 +1,141 @@
+//! Glob include/exclude patterns for [`LatexInput::add_folder_filtered`](crate::LatexInput::add_folder_filtered).
+//!
+//! Compiles glob patterns to byte `Regex`es the way Mercurial's
+//! `filepatterns` module does it: every byte is escaped through a
+//! 256-entry table (itself, or prefixed with `\` if it is a regex
+//! metacharacter), then a small set of glob tokens is substituted in
+//! order as the pattern is scanned left to right:
+//!
+//! - `**/` → `(?:.*/)?`
+//! - `*/` → `(?:.*/)?`
+//! - `**` → `.*`
+//! - `*`  → `[^/]*`
+//! - `?`  → `[^/]`
+//! - `[...]` character classes are passed through untouched
+//!
+//! The result is anchored with `^...$` so a pattern must match the whole
+//! stored path, not just a part of it.
+
+use regex::bytes::Regex;
+
+use crate::{LatexError, Result};
+
+/// Regex metacharacters that need escaping when copied into the
+/// generated pattern literally.
+const SPECIAL: &[u8] = b".^$|()[]{}*+?\\";
+
+/// Build the 256-entry escape table: `table[b]` is how byte `b` should
+/// be written into the generated regex.
+fn escape_table() -> Vec<Vec<u8>> {
+    (0..=255u16)
+        .map(|b| {
+            let byte = b as u8;
+            if SPECIAL.contains(&byte) {
+                vec![b'\\', byte]
+            } else {
+                vec![byte]
+            }
+        })
+        .collect()
+}
+
+/// Translate a single glob pattern into an anchored regex source string.
+fn glob_to_regex(pattern: &str) -> String {
+    let table = escape_table();
+    let bytes = pattern.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'*') && bytes.get(i + 2) == Some(&b'/') {
+            out.extend_from_slice(b"(?:.*/)?");
+            i += 3;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            out.extend_from_slice(b"(?:.*/)?");
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'*') {
+            out.extend_from_slice(b".*");
+            i += 2;
+        } else if bytes[i] == b'*' {
+            out.extend_from_slice(b"[^/]*");
+            i += 1;
+        } else if bytes[i] == b'?' {
+            out.extend_from_slice(b"[^/]");
+            i += 1;
+        } else if bytes[i] == b'[' {
+            let start = i;
+            i += 1;
+            while i < bytes.len() && bytes[i] != b']' {
+                i += 1;
+            }
+            if i < bytes.len() {
+                i += 1;
+            }
+            out.extend_from_slice(&bytes[start..i]);
+        } else {
+            out.extend_from_slice(&table[bytes[i] as usize]);
+            i += 1;
+        }
+    }
+
+    format!("^{}$", String::from_utf8_lossy(&out))
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(&glob_to_regex(pattern))
+        .map_err(|_| LatexError::LatexError(format!("Invalid glob pattern `{}`.", pattern)))
+}
+
+/// A compiled set of include/exclude globs, checked against a path's
+/// stored name.
+pub(crate) struct PatternSet {
+    includes: Vec<Regex>,
+    excludes: Vec<Regex>,
+}
+
+impl PatternSet {
+    /// Compile `includes` and `excludes`. An empty `includes` list means
+    /// "include everything" rather than "include nothing".
+    pub(crate) fn compile(includes: &[&str], excludes: &[&str]) -> Result<PatternSet> {
+        Ok(PatternSet {
+            includes: includes.iter().map(|p| compile(p)).collect::<Result<_>>()?,
+            excludes: excludes.iter().map(|p| compile(p)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Whether `name` is matched by the include set (or the set is
+    /// empty) and not matched by the exclude set.
+    pub(crate) fn is_allowed(&self, name: &str) -> bool {
+        let bytes = name.as_bytes();
+        let included = self.includes.is_empty() || self.includes.iter().any(|re| re.is_match(bytes));
+        let excluded = self.excludes.iter().any(|re| re.is_match(bytes));
+        included && !excluded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_doublestar_slash_matches_root_and_nested_files() {
+        let patterns = PatternSet::compile(&["**/*.tex"], &[]).unwrap();
+        assert!(patterns.is_allowed("main.tex"));
+        assert!(patterns.is_allowed("assets/main.tex"));
+        assert!(patterns.is_allowed("assets/nested/main.tex"));
+        assert!(!patterns.is_allowed("assets/logo.png"));
+    }
+
+    #[test]
+    fn test_glob_doublestar_excludes_whole_directory() {
+        let patterns = PatternSet::compile(&[], &["**/build/**"]).unwrap();
+        assert!(patterns.is_allowed("assets/main.tex"));
+        assert!(!patterns.is_allowed("assets/build/main.pdf"));
+    }
+
+    #[test]
+    fn test_glob_empty_includes_means_include_everything() {
+        let patterns = PatternSet::compile(&[], &[]).unwrap();
+        assert!(patterns.is_allowed("anything.tex"));
+    }
+}
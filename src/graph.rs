@@ -0,0 +1,236 @@
+//! Dependency resolution for a [`LatexInput`](crate::LatexInput).
+//!
+//! Real documents rarely consist of a single file: the main document pulls
+//! in subfiles and assets through `\input{…}`, `\include{…}`,
+//! `\usepackage{…}` and `\includegraphics{…}`. This module scans the
+//! provided buffers for those commands and builds a small directed graph of
+//! documents, modeled after texlab's workspace graph: nodes are entries of
+//! the `LatexInput`, edges are include relations, and the full set that
+//! must be materialized for a compile is the depth-first walk from the
+//! root document.
+
+use regex::bytes::Regex;
+use std::collections::HashMap;
+
+use crate::{LatexError, LatexInput, Result};
+
+/// Commands that reference another `.tex` file which must exist, in the
+/// order they should be tried against the buffer.
+const INPUT_COMMANDS: &[&str] = &["input", "include"];
+
+/// A directed graph of documents linked by include-like commands.
+pub(crate) struct Graph<'a> {
+    input: &'a LatexInput,
+    edges: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Graph<'a> {
+    /// Scan every entry of `input` for include-like commands and resolve
+    /// them against the other entries, then walk the graph depth-first
+    /// from `main`.
+    ///
+    /// Returns the transitive set of file names (including `main` itself)
+    /// that must be materialized into the compile working directory, in
+    /// DFS order. Fails with [`LatexError::MissingDependency`] listing
+    /// every referenced-but-missing file reachable from `main`.
+    pub(crate) fn resolve(main: &str, input: &LatexInput) -> Result<Vec<String>> {
+        let graph = Graph {
+            input,
+            edges: HashMap::new(),
+        };
+        graph.dfs(main)
+    }
+
+    fn dfs(mut self, main: &str) -> Result<Vec<String>> {
+        let root = self
+            .resolve_name(main, &[".tex"])
+            .ok_or_else(|| LatexError::MissingDependency(vec![main.to_string()]))?;
+
+        let mut visited = Vec::new();
+        let mut missing = Vec::new();
+        let mut stack = vec![root];
+
+        while let Some(name) = stack.pop() {
+            if visited.contains(&name) {
+                continue;
+            }
+            let (refs, unresolved) = self.scan(&name);
+            self.edges.insert(name.clone(), refs.clone());
+            missing.extend(unresolved);
+            visited.push(name.clone());
+
+            for next in refs.into_iter().rev() {
+                if !visited.contains(&next) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(LatexError::MissingDependency(missing));
+        }
+        Ok(visited)
+    }
+
+    /// Scan the buffer stored for `name` and resolve every reference it
+    /// contains. Returns the resolved dependencies together with any
+    /// reference that could not be matched against `self.input`.
+    fn scan(&self, name: &str) -> (Vec<String>, Vec<String>) {
+        let content = match self.buffer(name) {
+            Some(content) => content,
+            None => return (Vec::new(), Vec::new()),
+        };
+
+        let mut resolved = Vec::new();
+        let mut missing = Vec::new();
+
+        for cmd in INPUT_COMMANDS {
+            for raw in capture_refs(cmd, content) {
+                match self.resolve_name(&raw, &[".tex"]) {
+                    Some(found) => resolved.push(found),
+                    None => missing.push(raw),
+                }
+            }
+        }
+
+        for raw in capture_refs("includegraphics", content) {
+            match self.resolve_name(&raw, &[".pdf", ".png", ".jpg", ".jpeg", ".eps"]) {
+                Some(found) => resolved.push(found),
+                None => missing.push(raw),
+            }
+        }
+
+        // `\usepackage` may refer to a bundled `.sty` file or to a package
+        // installed in the TeX distribution; only the former is a real
+        // dependency of this input, the latter is left to the engine.
+        for raw in capture_refs("usepackage", content) {
+            if let Some(found) = self.resolve_name(&raw, &[".sty"]) {
+                resolved.push(found);
+            }
+        }
+
+        (resolved, missing)
+    }
+
+    fn buffer(&self, name: &str) -> Option<&[u8]> {
+        self.input
+            .input
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, buf)| buf.as_slice())
+    }
+
+    fn find(&self, name: &str) -> Option<String> {
+        self.input
+            .input
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(n, _)| n.clone())
+    }
+
+    /// Resolve a raw reference (as it appears in the source, without a
+    /// guaranteed extension or matching relative path) against the stored
+    /// input entries.
+    ///
+    /// Tries, in order: the raw name as-is, the raw name with each of
+    /// `exts` appended, and finally a suffix match on the stored name so
+    /// that `\input{sub/chapter}` still finds an entry stored as
+    /// `assets/sub/chapter.tex`.
+    fn resolve_name(&self, raw: &str, exts: &[&str]) -> Option<String> {
+        let mut candidates = vec![raw.to_string()];
+        candidates.extend(exts.iter().map(|ext| format!("{}{}", raw, ext)));
+
+        for candidate in &candidates {
+            if let Some(found) = self.find(candidate) {
+                return Some(found);
+            }
+        }
+
+        for candidate in &candidates {
+            if let Some(found) = self
+                .input
+                .input
+                .iter()
+                .map(|(n, _)| n)
+                .find(|name| is_path_suffix(name, candidate))
+            {
+                return Some(found.clone());
+            }
+        }
+
+        None
+    }
+}
+
+/// Whether `candidate` matches `name` or one of its path components,
+/// i.e. `name == candidate` or `name` ends with `/candidate`. A plain
+/// `ends_with` would also match `oldchapter.tex` against `chapter.tex`.
+fn is_path_suffix(name: &str, candidate: &str) -> bool {
+    name == candidate || name.ends_with(&format!("/{}", candidate))
+}
+
+/// Extract the brace-delimited argument of every occurrence of `\cmd` in
+/// `content`, ignoring an optional leading `[...]` argument.
+fn capture_refs(cmd: &str, content: &[u8]) -> Vec<String> {
+    let pattern = format!(r"\\{}(?:\[[^\]]*\])?\{{([^}}]+)\}}", regex::escape(cmd));
+    let re = match Regex::new(&pattern) {
+        Ok(re) => re,
+        Err(_) => return Vec::new(),
+    };
+
+    re.captures_iter(content)
+        .filter_map(|c| c.get(1))
+        .filter_map(|m| std::str::from_utf8(m.as_bytes()).ok())
+        .flat_map(|names| names.split(',').map(str::trim).map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LatexInput;
+
+    fn input(files: &[(&str, &str)]) -> LatexInput {
+        let mut input = LatexInput::new();
+        for (name, content) in files {
+            input.add(name, content.as_bytes().to_vec());
+        }
+        input
+    }
+
+    #[test]
+    fn test_resolve_finds_transitive_files() {
+        let input = input(&[
+            ("main.tex", r"\input{chapter}\includegraphics{logo}"),
+            ("chapter.tex", "chapter content"),
+            ("logo.png", "not really a png"),
+        ]);
+
+        let files = Graph::resolve("main.tex", &input).unwrap();
+        assert_eq!(files, vec!["main.tex".to_string(), "chapter.tex".to_string(), "logo.png".to_string()]);
+    }
+
+    #[test]
+    fn test_resolve_errors_on_missing_reference() {
+        let input = input(&[("main.tex", r"\input{missing}")]);
+
+        let err = Graph::resolve("main.tex", &input).unwrap_err();
+        match err {
+            LatexError::MissingDependency(missing) => assert_eq!(missing, vec!["missing".to_string()]),
+            other => panic!("expected MissingDependency, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_name_prefers_exact_path_over_suffix_match() {
+        let input = input(&[
+            ("main.tex", r"\input{chapter}"),
+            ("oldchapter.tex", "stale"),
+            ("sub/chapter.tex", "current"),
+        ]);
+
+        let files = Graph::resolve("main.tex", &input).unwrap();
+        assert!(files.contains(&"sub/chapter.tex".to_string()));
+        assert!(!files.contains(&"oldchapter.tex".to_string()));
+    }
+}
@@ -26,7 +26,10 @@
 //!
 //! ## Example
 //!
-//! ```
+//! Requires a TeX engine (`pdflatex`, `xelatex` or `lualatex`) on `PATH`,
+//! so this example is `no_run` under `cargo test`.
+//!
+//! ```no_run
 //! use std::collections::HashMap;
 //! use std::fs::write;
 //! use latexcompile::{LatexCompiler, LatexInput, LatexError};
@@ -47,19 +50,36 @@
 //! }
 //! ```
 //!
+// The `Fail` derive macro expands to impls that clippy's non_local_definitions
+// lint flags on newer toolchains; that's a property of the macro, not of the
+// types it's applied to here, so it's silenced crate-wide instead of per-use.
+#![allow(non_local_definitions)]
 extern crate failure;
 #[macro_use]
 extern crate failure_derive;
 extern crate regex;
 extern crate tempfile;
 
-use regex::bytes::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 use tempfile::{tempdir, TempDir};
 
+mod diagnostics;
+mod engine;
+mod filepattern;
+mod graph;
+mod template;
+
+pub use diagnostics::{Diagnostic, Severity};
+pub use engine::TexEngine;
+use filepattern::PatternSet;
+use graph::Graph;
+pub use template::{SimpleTemplate, TemplateEngine};
+
 /// Specify all error cases with the fail api.
 #[derive(Fail, Debug)]
 pub enum LatexError {
@@ -71,6 +91,12 @@ pub enum LatexError {
     EnviromentError,
     #[fail(display = "Failed to create temporary context. {}", _0)]
     ContextCreationError(#[cause] std::io::Error),
+    #[fail(display = "Missing dependencies referenced from the input: {:?}.", _0)]
+    MissingDependency(Vec<String>),
+    #[fail(display = "No usable TeX engine found on PATH, tried: {}.", _0)]
+    EngineNotFound(String),
+    #[fail(display = "Compilation produced diagnostics: {:?}.", diagnostics)]
+    Compilation { diagnostics: Vec<Diagnostic> },
     #[fail(display = "{}", _0)]
     Io(#[cause] std::io::Error),
     #[fail(display = "{}", _0)]
@@ -80,9 +106,6 @@ pub enum LatexError {
 /// result type alias idiom
 type Result<T> = std::result::Result<T, LatexError>;
 
-/// An alias for a command line
-type Cmd = (String, Vec<String>);
-
 /// The latex input provides the needed files
 /// as tuple vector with name, buffer as tuple.
 #[derive(Debug, PartialEq)]
@@ -90,6 +113,12 @@ pub struct LatexInput {
     input: Vec<(String, Vec<u8>)>
 }
 
+impl Default for LatexInput {
+    fn default() -> Self {
+        LatexInput::new()
+    }
+}
+
 impl LatexInput {
     pub fn new() -> LatexInput {
         LatexInput {
@@ -124,12 +153,9 @@ impl LatexInput {
     /// If the path is not a file or can't be converted to a string nothing is added and ok is returned.
     pub fn add_file(&mut self, file: PathBuf) -> Result<()> {
         if file.is_file() {
-            match file.to_str() {
-                Some(name) => {
-                    let content = fs::read(&file).map_err(LatexError::Io)?;
-                    self.input.push((name.to_string(), content));
-                }
-                None => {}
+            if let Some(name) = file.to_str() {
+                let content = fs::read(&file).map_err(LatexError::Io)?;
+                self.input.push((name.to_string(), content));
             }
         }
         Ok(())
@@ -153,9 +179,52 @@ impl LatexInput {
             for path in paths {
                 let p = path.map_err(LatexError::Io)?.path();
                 if p.is_file() {
-                    self.add_file(p);
+                    self.add_file(p)?;
+                } else if p.is_dir() {
+                    self.add_folder(p)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a whole folder as input, keeping only files whose stored name
+    /// matches `includes` (all files, if empty) and none of `excludes`.
+    ///
+    /// Patterns are globs such as `**/*.tex` or `**/build/**`, compiled
+    /// the same way as Mercurial's filepatterns: `*` and `?` behave as
+    /// usual, `**` matches across directory separators, and `*/` matches
+    /// an optional leading path.
+    ///
+    /// ## Example
+    /// ```
+    /// # use latexcompile::LatexInput;
+    /// fn main() {
+    ///   let mut input = LatexInput::new();
+    ///   input.add_folder_filtered(
+    ///       "assets".into(),
+    ///       &["**/*.tex", "**/*.png"],
+    ///       &["**/build/**"],
+    ///   ).unwrap();
+    /// }
+    /// ```
+    pub fn add_folder_filtered(&mut self, folder: PathBuf, includes: &[&str], excludes: &[&str]) -> Result<()> {
+        let patterns = PatternSet::compile(includes, excludes)?;
+        self.add_folder_with_patterns(folder, &patterns)
+    }
+
+    fn add_folder_with_patterns(&mut self, folder: PathBuf, patterns: &PatternSet) -> Result<()> {
+        if folder.is_dir() {
+            let paths = fs::read_dir(folder).map_err(LatexError::Io)?;
+
+            for path in paths {
+                let p = path.map_err(LatexError::Io)?.path();
+                if p.is_file() {
+                    if p.to_str().is_some_and(|name| patterns.is_allowed(name)) {
+                        self.add_file(p)?;
+                    }
                 } else if p.is_dir() {
-                    self.add_folder(p);
+                    self.add_folder_with_patterns(p, patterns)?;
                 }
             }
         }
@@ -169,287 +238,300 @@ impl<'a> From<&'a str> for LatexInput {
         let mut input = LatexInput::new();
         let path = PathBuf::from(s);
         if path.is_file() {
-            input.add_file(path);
+            let _ = input.add_file(path);
         } else if path.is_dir() {
-            input.add_folder(path);
+            let _ = input.add_folder(path);
         }
         input
     }
 }
 
 /// Internal type alias for the key value store
-type TemplateDict = HashMap<String, String>;
-
-
-/// The processor takes latex files as input and replaces
-/// matching placeholders (e.g. ##someVar##) with the real
-/// content provided as HashMap.
-struct TemplateProcessor {
-    regex: Regex,
+pub(crate) type TemplateDict = HashMap<String, String>;
+
+/// Default cap on how many times the engine is rerun to stabilize
+/// cross-references, see [`LatexCompiler::with_max_runs`].
+const DEFAULT_MAX_RUNS: u32 = 5;
+
+/// Default number of concurrent workers for `run_batch`, see
+/// [`LatexCompiler::with_jobs`].
+const DEFAULT_JOBS: usize = 4;
+
+/// The knobs that drive a single compile, independent of which working
+/// directory it runs in. Split out of `LatexCompiler` so `run_batch` can
+/// share one configuration across many isolated temp dirs and worker
+/// threads.
+#[derive(Clone)]
+struct CompileConfig {
+    engine: TexEngine,
+    shell_escape: bool,
+    extra_args: Vec<String>,
+    max_runs: u32,
+    template: Arc<dyn TemplateEngine>,
 }
 
-impl TemplateProcessor {
-    /// Characters allowed as variable names: "a-zAZ0-9-_"
-    fn new() -> Result<TemplateProcessor> {
-        Ok(TemplateProcessor {
-            regex: Regex::new(r"##[a-z|A-Z|\d|-|_]+##")
-                .or(Err(LatexError::LatexError("Failed to compile regex.".to_string())))?,
-        })
+impl CompileConfig {
+    /// build the command-line
+    ///
+    /// The engine derives `\jobname` (and therefore the `.log`/`.pdf` it
+    /// writes) from `main_file`'s basename alone, dropping any directory
+    /// component. Pass `-output-directory` explicitly so it still lands
+    /// next to `main_file` instead of directly in `working_dir` — every
+    /// input materialized under a folder (the normal case, since
+    /// `LatexInput::add_folder`/`LatexInput::from` store paths like
+    /// `"assets/main.tex"`) would otherwise write its output where
+    /// nothing later looks for it.
+    fn get_cmd(&self, working_dir: &Path, main_file: &str) -> Command {
+        let output_dir = Path::new(main_file).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+
+        let mut cmd = Command::new(self.engine.binary());
+        cmd.args(self.engine.default_args(self.shell_escape))
+            .arg(format!("-output-directory={}", output_dir.display()))
+            .args(&self.extra_args)
+            .arg(main_file)
+            .current_dir(working_dir);
+        cmd
     }
 
-
-    /// Replace placeholders with their actual value or nothing if no replacement
-    /// is provided. The content is duplicated within this step.
-    fn process_placeholders(
-        &self,
-        content: &[u8],
-        dict: &TemplateDict,
-    ) -> Result<Vec<u8>> {
-        if !dict.is_empty() {
-            return Ok(content.into())
-        }
-        let mut replaced = vec![];
-
-        let mut running_index = 0;
-        for c in self.regex.captures_iter(content) {
-            let _match = c.get(0).unwrap();
-            //ok_or(Err(CompilerError::TemplatingError("Unable to get regex match.".to_string())))?;
-            let key = &content[_match.start() + 2.._match.end() - 2];
-            replaced.extend_from_slice(&content[running_index.._match.start()]);
-            println!("found {:?}\n", key);
-
-            let key_str = &std::str::from_utf8(key).map_err(LatexError::Utf8)?;
-            match dict.get(*key_str) {
-                Some(value) => {
-                    replaced.extend_from_slice(value.as_bytes());
-                }
-                None => {}
+    /// Run the templating step over every file the compile needs and
+    /// write the results into `working_dir`, preserving the directory
+    /// structure the files were stored under.
+    fn materialize(&self, working_dir: &Path, input: &LatexInput, files: &[String]) -> Result<()> {
+        for name in files {
+            let (_, content) = input
+                .input
+                .iter()
+                .find(|(n, _)| n == name)
+                .expect("file returned by the dependency graph must exist in the input");
+            let processed = self.template.process(name, content)?;
+
+            let destination = working_dir.join(name);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).map_err(LatexError::Io)?;
             }
-            running_index = _match.end();
+            fs::write(destination, processed).map_err(LatexError::Io)?;
         }
-        replaced.extend_from_slice(&content[running_index..]);
+        Ok(())
+    }
+
+    /// Resolve, materialize and compile `main` within `working_dir`,
+    /// rerunning the engine until cross-references stabilize or
+    /// `max_runs` is hit.
+    fn compile(&self, working_dir: &Path, main: &str, input: &LatexInput) -> Result<Vec<u8>> {
+        let files = Graph::resolve(main, input)?;
+        let main = &files[0];
+
+        self.materialize(working_dir, input, &files)?;
+
+        let log_path = working_dir.join(with_extension(main, "log"));
+        run_until_stable(self.max_runs, || {
+            self.get_cmd(working_dir, main).status().map_err(LatexError::Io)?;
+            Ok(fs::read_to_string(&log_path).unwrap_or_default())
+        })?;
+
+        fs::read(working_dir.join(with_extension(main, "pdf"))).map_err(LatexError::Io)
+    }
+}
 
-        Ok(replaced)
+/// Drive the rerun loop: call `run_once` to run the engine for a single
+/// pass and get back that pass's log, up to `max_runs` times, stopping
+/// early once a log reports an error or no longer asks for a rerun.
+///
+/// Split out of `CompileConfig::compile` so the loop/limit logic is
+/// covered by `cargo test` without shelling out to a real TeX install.
+fn run_until_stable(max_runs: u32, mut run_once: impl FnMut() -> Result<String>) -> Result<()> {
+    for _ in 0..max_runs.max(1) {
+        let log = run_once()?;
+        let diagnostics = diagnostics::parse(&log);
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            return Err(LatexError::Compilation { diagnostics });
+        }
+        if !diagnostics::needs_rerun(&log) {
+            break;
+        }
     }
+    Ok(())
 }
+
 /// The wrapper struct around some latex compiler.
 /// It provides a clean temporary enviroment for the
 /// latex compilation.
 pub struct LatexCompiler {
     working_dir: TempDir,
-    cmd: Cmd,
-    tp: TemplateProcessor,
-    dict: TemplateDict,
+    config: CompileConfig,
+    jobs: usize,
 }
 
 impl LatexCompiler {
-    /// Create a new latex compiler wrapper
+    /// Create a new latex compiler wrapper.
+    ///
+    /// Probes `PATH` for a usable engine, trying `pdflatex`, `xelatex`
+    /// and `lualatex` in turn, and fails with `LatexError::EngineNotFound`
+    /// if none of them is installed. Templating uses the bundled
+    /// `SimpleTemplate` with its default `##var##` delimiters; call
+    /// `with_template_engine` to plug in something else.
     pub fn new(dict: TemplateDict) -> Result<LatexCompiler> {
         let dir = tempdir().map_err(LatexError::Io)?;
-        let cmd = ("pdflatex".into(), vec!["-interaction=nonstopmode".into()]);
+        let engine = TexEngine::resolve(None)?;
 
         Ok(LatexCompiler {
             working_dir: dir,
-            cmd: cmd,
-            tp: TemplateProcessor::new()?,
-            dict: dict,
+            config: CompileConfig {
+                engine,
+                shell_escape: false,
+                extra_args: vec![],
+                max_runs: DEFAULT_MAX_RUNS,
+                template: Arc::new(SimpleTemplate::new(dict)?),
+            },
+            jobs: DEFAULT_JOBS,
         })
     }
 
-    /// Overwrite the default command-line `pdflatex`
+    /// Use a custom template engine instead of the bundled
+    /// `SimpleTemplate`.
+    pub fn with_template_engine(mut self, template: Box<dyn TemplateEngine>) -> Self {
+        self.config.template = Arc::from(template);
+        self
+    }
+
+    /// Use a specific engine instead of the auto-detected default.
+    ///
+    /// `TexEngine::PdfLatex`/`XeLatex`/`LuaLatex` still fall back to the
+    /// other known engines if the requested one is absent from `PATH`;
+    /// `TexEngine::Custom` is used as given or rejected if it can't be
+    /// found.
+    pub fn with_engine(mut self, engine: TexEngine) -> Result<Self> {
+        self.config.engine = TexEngine::resolve(Some(engine))?;
+        Ok(self)
+    }
+
+    /// Pass `-shell-escape` to the engine. Off by default, since it lets
+    /// the document run arbitrary shell commands during compilation.
+    pub fn with_shell_escape(mut self, enabled: bool) -> Self {
+        self.config.shell_escape = enabled;
+        self
+    }
+
+    /// Overwrite the engine with an arbitrary command-line, bypassing
+    /// engine detection entirely.
     pub fn with_cmd(mut self, cmd: &str) -> Self {
-        self.cmd.0 = cmd.into();
+        self.config.engine = TexEngine::Custom(cmd.into());
         self
     }
 
     /// Clean the arguments list and add a new argument.
     /// Use add_arg to add further arguments
     pub fn with_args(mut self, cmd: &str) -> Self {
-        self.cmd.1 = vec![cmd.into()];
+        self.config.extra_args = vec![cmd.into()];
         self
     }
 
     /// Add a new argument to the command-line.
     pub fn add_arg(mut self, cmd: &str) -> Self {
-        self.cmd.1.push(cmd.into());
+        self.config.extra_args.push(cmd.into());
         self
     }
 
-    /// build the command-line
-    fn get_cmd(&self, main_file: &str) -> Command {
-        let mut cmd = Command::new(&self.cmd.0);
-        cmd.args(&self.cmd.1)
-            .arg(main_file)
-            .current_dir(self.working_dir.path());
-        cmd
+    /// Cap how many times the engine is rerun to stabilize
+    /// cross-references (tables of contents, `\ref`s, bibliographies).
+    /// Defaults to `5`.
+    pub fn with_max_runs(mut self, max_runs: u32) -> Self {
+        self.config.max_runs = max_runs;
+        self
     }
 
-    pub fn run(&self, main: &str, input: &LatexInput) -> Result<Vec<u8>> {
-        // prepare sources
-        Err(LatexError::LatexError("No input files provided.".into()))
+    /// Cap how many jobs `run_batch` compiles concurrently. Defaults to
+    /// `4`.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
     }
-}
- /*       for file in files.iter() {
-            let source_dir = source_path.unwrap_or(|| {
-                let source = Path::new(&files[0]);
-                source.is_dir() {
-                    source
-                } else {
-                    source.parent().unwrap_or(Path::new("/"))
-                }
-            });
-       //     self.preprocess_input(file, source);
-        }
 
-        // first and second run
-        self.ctx.get_cmd().status().map_err(CompilerError::Io)?;
-        self.ctx.get_cmd().status().map_err(CompilerError::Io)?;
-
-        // get name of the result file
-        let result_name = self.ctx.get_result_name(suffix.unwrap_or(".pdf")).ok_or(CompilerError::CompilationError)?;
-
-        // copy result file
-        // let output = ::std::env::current_dir().map(|dir| dir.join(output_name)).map_err(CompilerError::Io)?;
-        // copy(result_name, output)
-        //     .map_err(CompilerError::Io)?;
-
-        Ok(self.ctx.working_dir.path().join(result_name))
-*/
- //   }
-/*
-    /// The preprocessing copies the provided files or folder structures
-    /// into the temporary working directory. Normal text files gets checked
-    /// for replacements by the templating processor.
-    fn preprocess_input(&self, file: &PathBuf, source_dir: &PathBuf) -> Result<()> {
-        let path = Path::new(file);
-        let metadata = path.metadata().expect("metadata call failed");
-        let destination = self.ctx.working_dir.path().join(
-            src_file
-                .strip_prefix(self.ctx.source_dir)
-                .or(Err(CompilerError::TemplatingError("Unable to strip prefix.".to_string())))?
-        );
-
-        if path.is_file() {
-            self.preprocess_file(&path, &destination)?;
-
-        } else if path.is_dir() {
-let paths = read_dir(path)
-.or(Err(CompilerError::TemplatingError(format!("Failed to read directory {:?}.", path).to_string())))?;
-            create_dir(destination).map_err(CompilerError::Io)?;
-            for path in paths {
-                    let src_file = path
-                    .or(Err(CompilerError::TemplatingError("Unable to get source file path.".to_string())))?.path();
-                self.preprocess_input(&src_file, source_dir)?;
-            }
-        } else {
-            Error(CompilerError::TemplatingError("Neither a file nor a directory.".into()))
-        }
-        Ok(())
+    pub fn run(&self, main: &str, input: &LatexInput) -> Result<Vec<u8>> {
+        self.config.compile(self.working_dir.path(), main, input)
     }
 
-    fn preprocess_file(&self, path: &Path, destination: &Path) -> Result<()> {
-        let mut content = String::new();
-        let mut src_file = File::open(path)
-            .or(Err(CompilerError::TemplatingError("Unable to open source file.".to_string())))?;
-
-        match src_file.read_to_string(&mut content) {
-            Err(_) => {
-                // maybe binary data, so just copy it
-                copy(&src, &dst).map_err(CompilerError::Io)?;
-                //.or(Err(CompilerError::TemplatingError("Unable to copy file.".to_string())))?;
-            }
-            Ok(_) => {
-                let replaced_content = self.tp.process_placeholders(&content, &self.dict)?;
-                //                        self.tp.process_sources(&self.ctx, &self.dict, files)?;
-                File::create(dst)
-                    .and_then(|mut f| f.write_all(replaced_content.as_bytes()))
-                    .or(Err(CompilerError::TemplatingError("Unable to create destination file.".to_string())))?;
-            }
-        }
-        Ok(())
-    }*/
-
-
-/*
-    /// Replace variables for all files within the template path and
-    /// copy the results into the created enviroment.
-    // TODO Handle folders
-    fn process_sources(&self, ctx: &Context, dict: &HashMap<String, String>, files: &[u8]) -> Result<()> {
-        let paths = read_dir(&ctx.source_dir)
-            .or(Err(CompilerError::TemplatingError("Failed to read template directory.".to_string())))?;
-        for path in paths {
-            let src_file = path
-                .or(Err(CompilerError::TemplatingError("Unable to get source file path.".to_string())))?.path();
-    let dst_file = ctx.working_dir.path().join(
-    src_file
-    .strip_prefix(&ctx.source_dir)
-    .or(Err(CompilerError::TemplatingError("Unable to strip prefix.".to_string())))?
-);
-
-    self.process_file(&src_file, &dst_file, &dict)?;
-}
-
-        Ok(())
+    /// Compile many documents against this compiler's configuration,
+    /// each in its own isolated temp dir, spread across up to
+    /// `with_jobs` worker threads.
+    ///
+    /// Useful for mail-merge / certificate-generation use cases where
+    /// the same template is compiled against hundreds of dictionaries,
+    /// the way CorTeX's importer processes large TeX collections. One
+    /// job failing does not abort the rest; each job's outcome is
+    /// reported at its original index.
+    pub fn run_batch(&self, jobs: Vec<(String, LatexInput)>) -> Vec<Result<Vec<u8>>> {
+        let config = self.config.clone();
+        run_concurrently(jobs, self.jobs, move |(main, input)| {
+            tempdir().map_err(LatexError::Io).and_then(|dir| config.compile(dir.path(), &main, &input))
+        })
     }
+}
 
-    /// Process a single file. If the file is a non-text file it is copied into the
-    /// destination enviroment, otherwise all placeholders are replaced with their
-    /// actual value.
-    fn process_file(&self, src: &Path, dst: &Path, dict: &HashMap<String, String>) -> Result<()> {
-        let mut content = String::new();
-        let mut src_file = File::open(src)
-            .or(Err(CompilerError::TemplatingError("Unable to open source file.".to_string())))?;
-
-        match src_file.read_to_string(&mut content) {
-            Err(_) => {
-                // maybe binary data, so just copy it
-                copy(&src, &dst).map_err(CompilerError::Io)?;//.or(Err(CompilerError::TemplatingError("Unable to copy file.".to_string())))?;
-            }
-            Ok(_) => {
-                let replaced_content = self.process_placeholders(&content, &dict)?;
-                File::create(dst)
-                    .and_then(|mut f| f.write_all(replaced_content.as_bytes()))
-                    .or(Err(CompilerError::TemplatingError("Unable to create destination file.".to_string())))?;
-            }
-        }
+/// Replace `path`'s extension, keeping its directory and file stem.
+fn with_extension(path: &str, ext: &str) -> PathBuf {
+    let mut path = PathBuf::from(path);
+    path.set_extension(ext);
+    path
+}
 
-        Ok(())
+/// Run `compile_one` over `jobs` across up to `worker_count` threads,
+/// reporting every job's outcome at its original index. One job failing
+/// does not abort the rest.
+///
+/// Split out of `LatexCompiler::run_batch` so the concurrency/isolation
+/// behavior is covered by `cargo test` independent of `CompileConfig::compile`
+/// shelling out to a real TeX install.
+fn run_concurrently<T, F>(jobs: Vec<T>, worker_count: usize, compile_one: F) -> Vec<Result<Vec<u8>>>
+where
+    T: Send + 'static,
+    F: Fn(T) -> Result<Vec<u8>> + Send + Sync + 'static,
+{
+    let total = jobs.len();
+    if total == 0 {
+        return Vec::new();
     }
-
-    /// Replace placeholders with their actual value or nothing if no replacement
-    /// is provided. The content is duplicated within this step.
-    fn process_placeholders(
-        &self,
-        content: &str,
-        dict: &HashMap<String, String>,
-    ) -> Result<String> {
-        if !dict.is_empty() {
-            return Ok(content.into())
-        }
-        let mut replaced = String::new();
-
-        let mut running_index = 0;
-        for c in self.regex.captures_iter(&content) {
-            let _match = c.get(0).unwrap();
-            //ok_or(Err(CompilerError::TemplatingError("Unable to get regex match.".to_string())))?;
-            let key = &content[_match.start() + 2.._match.end() - 2];
-            replaced += &content[running_index.._match.start()];
-            println!("found {:?}\n", key);
-
-            match dict.get(key) {
-                Some(value) => {
-                    replaced += value;
+    let worker_count = worker_count.max(1).min(total);
+
+    let queue: VecDeque<(usize, T)> = jobs.into_iter().enumerate().collect();
+    let queue = Arc::new(Mutex::new(queue));
+    let compile_one = Arc::new(compile_one);
+
+    let (result_tx, result_rx) = mpsc::channel::<(usize, Result<Vec<u8>>)>();
+
+    let handles: Vec<_> = (0..worker_count)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let result_tx = result_tx.clone();
+            let compile_one = Arc::clone(&compile_one);
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap().pop_front();
+                let (index, job) = match job {
+                    Some(job) => job,
+                    None => break,
+                };
+                let result = compile_one(job);
+                if result_tx.send((index, result)).is_err() {
+                    break;
                 }
-                None => {}
-            }
-            running_index = _match.end();
-        }
-        replaced += &content[running_index..];
+            })
+        })
+        .collect();
+    drop(result_tx);
 
-        Ok(replaced)
-    }*/
-//}
+    let mut results: Vec<Option<Result<Vec<u8>>>> = (0..total).map(|_| None).collect();
+    for (index, result) in result_rx {
+        results[index] = Some(result);
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
 
+    results
+        .into_iter()
+        .map(|r| r.expect("every queued job yields exactly one result"))
+        .collect()
+}
 
 #[cfg(test)]
 mod tests {
@@ -470,7 +552,7 @@ mod tests {
         let buf = include_bytes!("../assets/main.tex");
         let expected = LatexInput{ input: vec![("assets/main.tex".into(), buf.to_vec())]};
         let mut input = LatexInput::new();
-        input.add_file(PathBuf::from("assets/main.tex"));
+        input.add_file(PathBuf::from("assets/main.tex")).unwrap();
         assert_eq!(input, expected);
     }
 
@@ -479,7 +561,7 @@ mod tests {
         let buf = include_bytes!("../assets/main.tex");
         let expected = LatexInput{ input: vec![("assets/nested/main.tex".into(), buf.to_vec())]};
         let mut input = LatexInput::new();
-        input.add_folder(PathBuf::from("assets/nested"));
+        input.add_folder(PathBuf::from("assets/nested")).unwrap();
         assert_eq!(input, expected);
     }
 
@@ -489,36 +571,95 @@ mod tests {
         let buf2 = include_bytes!("../assets/logo.png");
         let buf3 = include_bytes!("../assets/card.tex");
         let buf4 = include_bytes!("../assets/nested/main.tex");
-        let expected = LatexInput{
-            input: vec![("assets/nested/main.tex".into(), buf4.to_vec()),
-                        ("assets/main.tex".into(), buf1.to_vec()),
-                        ("assets/logo.png".into(), buf2.to_vec()),
-                        ("assets/card.tex".into(), buf3.to_vec())]
-        };
+        let mut expected = vec![("assets/nested/main.tex".to_string(), buf4.to_vec()),
+                    ("assets/main.tex".to_string(), buf1.to_vec()),
+                    ("assets/logo.png".to_string(), buf2.to_vec()),
+                    ("assets/card.tex".to_string(), buf3.to_vec())];
         let mut input = LatexInput::new();
-        input.add_folder(PathBuf::from("assets"));
-        assert_eq!(input, expected);
+        input.add_folder(PathBuf::from("assets")).unwrap();
+
+        // `fs::read_dir` doesn't guarantee an enumeration order, so compare
+        // the entries as a set rather than relying on a fixed order.
+        let mut actual = input.input;
+        actual.sort();
+        expected.sort();
+        assert_eq!(actual, expected);
     }
 
     #[test]
     fn test_empty_templating() {
-        let templating = TemplateProcessor::new();
+        let templating = SimpleTemplate::new(HashMap::new());
         assert!(templating.is_ok());
-        let map = HashMap::new();
         let buf = include_bytes!("../assets/main.tex");
-        let res = templating.unwrap().process_placeholders(buf, &map);
+        let res = templating.unwrap().process("main.tex", buf);
         assert!(res.is_ok());
         assert_eq!(res.unwrap(), buf.to_vec());
     }
 
-    /*
     #[test]
-    fn test_context_cmd() {
-        let mut context = Context::new(PathBuf::new(), "".into());
-        assert!(context.is_ok());
-        let context = context.unwrap().with_cmd("latexmk").with_args("arg1").add_arg("arg2");
-        let ctx = ("latexmk".into(), vec!["arg1".into(), "arg2".into()]);
-        assert_eq!(context.cmd, ctx);
-    }
-*/
+    fn test_run_until_stable_stops_once_no_rerun_is_needed() {
+        let mut calls = 0;
+        let result = run_until_stable(5, || {
+            calls += 1;
+            Ok("Output written on main.pdf (1 page).".to_string())
+        });
+        assert!(result.is_ok());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_run_until_stable_caps_reruns_at_max_runs() {
+        let mut calls = 0;
+        let result = run_until_stable(3, || {
+            calls += 1;
+            Ok("Rerun to get cross-references right.".to_string())
+        });
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_run_until_stable_stops_immediately_on_error() {
+        let mut calls = 0;
+        let result = run_until_stable(5, || {
+            calls += 1;
+            Ok("! Undefined control sequence.\nl.5 \\foo\n".to_string())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_run_concurrently_reports_every_job_at_its_original_index() {
+        let jobs: Vec<u32> = (0..20).collect();
+        let results = run_concurrently(jobs, 4, |n| Ok(vec![n as u8]));
+
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap(), vec![i as u8]);
+        }
+    }
+
+    #[test]
+    fn test_run_concurrently_one_failure_does_not_abort_the_rest() {
+        let jobs = vec![0, 1, 2, 3];
+        let results = run_concurrently(jobs, 2, |n| {
+            if n == 2 {
+                Err(LatexError::LatexError("boom".to_string()))
+            } else {
+                Ok(vec![n as u8])
+            }
+        });
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert!(results[2].is_err());
+        assert!(results[3].is_ok());
+    }
+
+    #[test]
+    fn test_run_concurrently_empty_jobs_yields_no_results() {
+        let jobs: Vec<u32> = vec![];
+        let results = run_concurrently(jobs, 4, |n| Ok(vec![n as u8]));
+        assert!(results.is_empty());
+    }
 }
@@ -0,0 +1,122 @@
+//! Pluggable templating.
+//!
+//! `LatexCompiler` runs every input file through a boxed `TemplateEngine`
+//! before handing it to the TeX engine, the same pluggable-preprocessor
+//! pattern mdBook uses for its preprocessors. The bundled `SimpleTemplate`
+//! replaces delimited placeholders (`##var##` by default) with values
+//! from a dictionary; implement `TemplateEngine` yourself to plug in a
+//! different templating syntax entirely.
+
+use regex::bytes::Regex;
+
+use crate::{LatexError, Result, TemplateDict};
+
+/// Preprocesses a single file's content before it is written into the
+/// compile working directory.
+///
+/// Requires `Send + Sync` since `LatexCompiler::run_batch` shares the
+/// engine across its worker threads.
+pub trait TemplateEngine: Send + Sync {
+    /// `name` is the file's stored name, for engines that only want to
+    /// touch certain files (e.g. skip binary assets).
+    fn process(&self, name: &str, content: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The bundled `TemplateEngine`: replaces `open`/`close`-delimited
+/// placeholders (e.g. `##someVar##`) with values from a `TemplateDict`.
+pub struct SimpleTemplate {
+    regex: Regex,
+    open_len: usize,
+    close_len: usize,
+    dict: TemplateDict,
+}
+
+impl SimpleTemplate {
+    /// Characters allowed as variable names: "a-zA-Z0-9-_". Uses the
+    /// classic `##var##` delimiters.
+    pub fn new(dict: TemplateDict) -> Result<SimpleTemplate> {
+        SimpleTemplate::with_delimiters("##", "##", dict)
+    }
+
+    /// Use different delimiters, e.g. `SimpleTemplate::with_delimiters("{{", "}}", dict)`
+    /// to reuse existing `{{mustache}}`-style templates and avoid
+    /// clashing with literal `#` usage in LaTeX.
+    pub fn with_delimiters(open: &str, close: &str, dict: TemplateDict) -> Result<SimpleTemplate> {
+        let pattern = format!(
+            r"{}[a-zA-Z\d\-_]+{}",
+            regex::escape(open),
+            regex::escape(close)
+        );
+        Ok(SimpleTemplate {
+            regex: Regex::new(&pattern)
+                .or(Err(LatexError::LatexError("Failed to compile regex.".to_string())))?,
+            open_len: open.len(),
+            close_len: close.len(),
+            dict,
+        })
+    }
+}
+
+impl TemplateEngine for SimpleTemplate {
+    /// Replace placeholders with their actual value or nothing if no
+    /// replacement is provided. The content is duplicated within this
+    /// step.
+    fn process(&self, _name: &str, content: &[u8]) -> Result<Vec<u8>> {
+        if self.dict.is_empty() {
+            return Ok(content.into());
+        }
+        let mut replaced = vec![];
+
+        let mut running_index = 0;
+        for c in self.regex.captures_iter(content) {
+            let _match = c.get(0).unwrap();
+            let key = &content[_match.start() + self.open_len.._match.end() - self.close_len];
+            replaced.extend_from_slice(&content[running_index.._match.start()]);
+
+            let key_str = &std::str::from_utf8(key).map_err(LatexError::Utf8)?;
+            if let Some(value) = self.dict.get(*key_str) {
+                replaced.extend_from_slice(value.as_bytes());
+            }
+            running_index = _match.end();
+        }
+        replaced.extend_from_slice(&content[running_index..]);
+
+        Ok(replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_simple_template_substitutes_known_placeholder() {
+        let mut dict = HashMap::new();
+        dict.insert("name".to_string(), "World".to_string());
+        let template = SimpleTemplate::new(dict).unwrap();
+
+        let result = template.process("x.tex", b"Hello ##name##!").unwrap();
+        assert_eq!(result, b"Hello World!".to_vec());
+    }
+
+    #[test]
+    fn test_simple_template_drops_unknown_placeholder() {
+        let mut dict = HashMap::new();
+        dict.insert("name".to_string(), "World".to_string());
+        let template = SimpleTemplate::new(dict).unwrap();
+
+        let result = template.process("x.tex", b"Hi ##other##!").unwrap();
+        assert_eq!(result, b"Hi !".to_vec());
+    }
+
+    #[test]
+    fn test_simple_template_with_custom_delimiters() {
+        let mut dict = HashMap::new();
+        dict.insert("name".to_string(), "World".to_string());
+        let template = SimpleTemplate::with_delimiters("{{", "}}", dict).unwrap();
+
+        let result = template.process("x.tex", b"Hello {{name}}!").unwrap();
+        assert_eq!(result, b"Hello World!".to_vec());
+    }
+}